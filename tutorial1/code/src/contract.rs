@@ -1,11 +1,17 @@
-use crate::msg::{HandleAnswer, HandleMsg, InitMsg, QueryAnswer, QueryMsg};
+use crate::expiration::Expiration;
+use crate::msg::{
+    BatchRecordItem, HandleAnswer, HandleMsg, InitMsg, MigrateMsg, QueryAnswer, QueryMsg,
+    QueryWithPermit, ReadQuery, ReadResult, ReminderWithId,
+};
+use crate::permit::{self, Permit};
 use crate::state::{
-    load, may_load, read_viewing_key, save, write_viewing_key, Reminder, State, CONFIG_KEY,
+    migrate_state, read_viewing_key, revoke_permit, write_viewing_key, Bincode2ReminderStore,
+    Bincode2ReminderStoreRef, Reminder, ReminderStore, ReminderStoreMut, State, CONTRACT_VERSION,
 };
 use crate::viewing_key::{ViewingKey, VIEWING_KEY_SIZE};
 use cosmwasm_std::{
-    to_binary, Api, Binary, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier,
-    QueryResult, StdError, StdResult, Storage,
+    to_binary, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, InitResponse,
+    MigrateResponse, Querier, QueryResult, StdError, StdResult, Storage,
 };
 use secret_toolkit::crypto::sha_256;
 use std::convert::TryFrom;
@@ -26,7 +32,7 @@ fn valid_max_size(val: i32) -> Option<u16> {
 // Init function
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
     // Check whether the reminder's maximum size exceeds its upper bound, i.e., a u16 type
@@ -42,97 +48,200 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     // New instantiation of the state function
     // store the 'seed' when the contract is initialized
     let config = State {
+        version: CONTRACT_VERSION,
         max_size,
         reminder_count: 0_u64,
         prng_seed: sha_256(base64::encode(msg.prng_seed).as_bytes()).to_vec(), // encode the 'seed' as a hashed Base64
+        // the contract keeps its own address from init time around for later use, e.g.
+        // validating SNIP-24 permits
+        contract_address: env.contract.address,
     };
 
     // Save the state function and send it to storage
-    save(&mut deps.storage, CONFIG_KEY, &config)?;
+    Bincode2ReminderStore::new(&mut deps.storage).set_config(&config)?;
 
     // Return a default 'InitResponse'
     Ok(InitResponse::default())
 }
 
+// -------------------------------------------------------------------------- //
+//                                   migrate                                  //
+// -------------------------------------------------------------------------- //
+// Upgrades on-chain state to the current `CONTRACT_VERSION` when the contract's code is
+// migrated, so storage-layout changes don't strand existing data.
+pub fn migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    _msg: MigrateMsg,
+) -> StdResult<MigrateResponse> {
+    let config = migrate_state(&deps.storage, env.contract.address)?;
+    Bincode2ReminderStore::new(&mut deps.storage).set_config(&config)?;
+
+    Ok(MigrateResponse::default())
+}
+
+// -------------------------------------------------------------------------- //
+//               shared reminder lookup (handle + query)                      //
+// -------------------------------------------------------------------------- //
+
+// The outcome of looking up an address's most recent reminder against the current block.
+enum ReminderLookup {
+    Found(Reminder),
+    Expired,
+    NotFound,
+}
+
+// Shared by `try_read`, `query_read`, and `query_batch_read`: fetch the sender's most recent
+// reminder and classify it against the current block. Callers decide what, if anything, to do
+// about an expired entry - `try_read` purges it, the read-only query paths can't since queries
+// never mutate state.
+fn lookup_most_recent<St: ReminderStore>(
+    store: &St,
+    env: &Env,
+    sender_address: &CanonicalAddr,
+) -> StdResult<ReminderLookup> {
+    let count = store.reminder_count(sender_address);
+    if count == 0 {
+        return Ok(ReminderLookup::NotFound);
+    }
+
+    Ok(match store.get_reminder(sender_address, count - 1)? {
+        Some(stored_reminder) if stored_reminder.expiration.is_expired(&env.block) => {
+            ReminderLookup::Expired
+        }
+        Some(stored_reminder) => ReminderLookup::Found(stored_reminder),
+        None => ReminderLookup::NotFound,
+    })
+}
+
+// Turn a `ReminderLookup` into the status/reminder/timestamp shape shared by `HandleAnswer::Read`
+// and `QueryAnswer::Read`/`QueryAnswer::BatchRead`. An expired reminder reads the same as one
+// that was never recorded.
+fn reminder_lookup_to_result(lookup: ReminderLookup) -> ReadResult {
+    match lookup {
+        ReminderLookup::Found(stored_reminder) => ReadResult {
+            status: String::from("Reminder found."),
+            reminder: String::from_utf8(stored_reminder.content).ok(),
+            timestamp: Some(stored_reminder.timestamp),
+        },
+        ReminderLookup::Expired | ReminderLookup::NotFound => ReadResult {
+            status: String::from("Reminder not found."),
+            reminder: None,
+            timestamp: None,
+        },
+    }
+}
+
 //  -------------------------------------------------------------------------- //
 //                                     handle                                  //
 //  -------------------------------------------------------------------------- //
 
+// Shared by `try_record` and `try_batch_record`: run the size-check, append the reminder to
+// the sender's list if it passes, and bump the store's reminder count. Operates through a
+// `ReminderStoreMut` instead of `deps` directly so it doesn't care how reminders are laid out.
+fn record_one<St: ReminderStoreMut>(
+    store: &mut St,
+    env: &Env,
+    sender_address: &CanonicalAddr,
+    content: &[u8],
+    expiration: Option<Expiration>,
+) -> StdResult<(String, Option<u32>)> {
+    let config = store.config()?;
+    if content.len() > config.max_size.into() {
+        // if reminder content is too long, set status message and do nothing else
+        return Ok((
+            String::from("Message is too long. Reminder not recorded."),
+            None,
+        ));
+    }
+
+    // create the reminder struct containing content string, timestamp, and expiration
+    let stored_reminder = Reminder {
+        content: content.to_vec(),
+        timestamp: env.block.time,
+        expiration: expiration.unwrap_or_default(),
+    };
+
+    // append the reminder to the sender's ordered reminder list instead of overwriting it
+    let index = store.put_reminder(sender_address, &stored_reminder)?;
+    store.bump_count()?;
+
+    Ok((String::from("Reminder recorded!"), Some(index)))
+}
+
 // Record the message if you can and abort it if message exceeds allowed size
 fn try_record<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     reminder: String,
+    expiration: Option<Expiration>,
 ) -> StdResult<HandleResponse> {
-    let status: String;
-    let reminder = reminder.as_bytes();
-
-    // retrieve the config state from storage
-    let mut config: State = load(&mut deps.storage, CONFIG_KEY)?;
+    // get the canonical address of sender
+    let sender_address = deps.api.canonical_address(&env.message.sender)?;
 
-    if reminder.len() > config.max_size.into() {
-        // if reminder content is too long, set status message and do nothing else
-        status = String::from("Message is too long. Reminder not recorded.");
-    } else {
-        // get the canonical address of sender
-        let sender_address = deps.api.canonical_address(&env.message.sender)?;
+    let mut store = Bincode2ReminderStore::new(&mut deps.storage);
+    let (status, index) = record_one(
+        &mut store,
+        &env,
+        &sender_address,
+        reminder.as_bytes(),
+        expiration,
+    )?;
 
-        // create the reminder struct containing content string and timestamp
-        let stored_reminder = Reminder {
-            content: reminder.to_vec(),
-            timestamp: env.block.time,
-        };
+    // Return a HandleResponse with the appropriate status message included in the data field
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Record { status, index })?),
+    })
+}
 
-        // save the reminder using a byte vector representation of the sender's address as the key
-        save(
-            &mut deps.storage,
-            &sender_address.as_slice().to_vec(),
-            &stored_reminder,
+// Record many reminders for the sender in a single transaction
+fn try_batch_record<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    reminders: Vec<BatchRecordItem>,
+) -> StdResult<HandleResponse> {
+    let sender_address = deps.api.canonical_address(&env.message.sender)?;
+    let mut store = Bincode2ReminderStore::new(&mut deps.storage);
+
+    let mut statuses = Vec::with_capacity(reminders.len());
+    for item in reminders {
+        let (status, _index) = record_one(
+            &mut store,
+            &env,
+            &sender_address,
+            item.content.as_bytes(),
+            item.expiration,
         )?;
-
-        // increment the reminder_count
-        config.reminder_count += 1;
-        save(&mut deps.storage, CONFIG_KEY, &config)?;
-
-        // set the status message
-        status = String::from("Reminder recorded!");
+        statuses.push(status);
     }
 
-    // Return a HandleResponse with the appropriate status message included in the data field
     Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::Record { status })?),
+        data: Some(to_binary(&HandleAnswer::BatchRecord { statuses })?),
     })
 }
 
-// Try and read the message if there is one
+// Try and read the message if there is one - returns the most recently recorded reminder
 fn try_read<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
 ) -> StdResult<HandleResponse> {
-    let status: String;
-    let mut reminder: Option<String> = None;
-    let mut timestamp: Option<u64> = None;
-
     let sender_address = deps.api.canonical_address(&env.message.sender)?;
+    let mut store = Bincode2ReminderStore::new(&mut deps.storage);
 
-    // read the reminder from storage
-    let result: Option<Reminder> = may_load(&mut deps.storage, &sender_address.as_slice().to_vec())
-        .ok()
-        .unwrap();
-    match result {
-        // set all response field values
-        Some(stored_reminder) => {
-            status = String::from("Reminder found.");
-            reminder = String::from_utf8(stored_reminder.content).ok();
-            timestamp = Some(stored_reminder.timestamp);
-        }
-        // unless there's an error
-        None => {
-            status = String::from("Reminder not found.");
-        }
-    };
+    let lookup = lookup_most_recent(&store, &env, &sender_address)?;
+    // an expired reminder is treated as not found, and purged so it doesn't linger
+    if let ReminderLookup::Expired = lookup {
+        store.remove_latest_reminder(&sender_address);
+    }
+    let ReadResult {
+        status,
+        reminder,
+        timestamp,
+    } = reminder_lookup_to_result(lookup);
 
     // Return a HandleResponse with status message, reminder, and timestamp included in the data field
     Ok(HandleResponse {
@@ -151,8 +260,7 @@ pub fn try_generate_viewing_key<S: Storage, A: Api, Q: Querier>(
     env: Env,
     entropy: String,
 ) -> StdResult<HandleResponse> {
-    let config: State = load(&mut deps.storage, CONFIG_KEY)?;
-    let prng_seed = config.prng_seed;
+    let prng_seed = Bincode2ReminderStore::new(&mut deps.storage).config()?.prng_seed;
 
     let key = ViewingKey::new(&env, &prng_seed, (&entropy).as_ref());
 
@@ -167,6 +275,24 @@ pub fn try_generate_viewing_key<S: Storage, A: Api, Q: Querier>(
     })
 }
 
+// Revoke a previously signed query permit by name, for the sender
+fn try_revoke_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    permit_name: String,
+) -> StdResult<HandleResponse> {
+    let sender_address = deps.api.canonical_address(&env.message.sender)?;
+    revoke_permit(&mut deps.storage, &sender_address, &permit_name);
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::RevokePermit {
+            status: String::from("Permit revoked."),
+        })?),
+    })
+}
+
 // 'handle' function
 pub fn handle<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
@@ -174,11 +300,16 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
     match msg {
-        HandleMsg::Record { reminder } => try_record(deps, env, reminder),
+        HandleMsg::Record {
+            reminder,
+            expiration,
+        } => try_record(deps, env, reminder, expiration),
         HandleMsg::Read {} => try_read(deps, env),
         HandleMsg::GenerateViewingKey { entropy, .. } => {
             try_generate_viewing_key(deps, env, entropy)
         }
+        HandleMsg::RevokePermit { permit_name } => try_revoke_permit(deps, env, permit_name),
+        HandleMsg::BatchRecord { reminders } => try_batch_record(deps, env, reminders),
     }
 }
 
@@ -186,19 +317,38 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
 //                                    query                                   //
 // -------------------------------------------------------------------------- //
 // A query function to return the binary encoded 'Stats' struct.
+// Takes the current `BlockInfo` (via `Env`) so read paths can tell whether a reminder has
+// expired - queries otherwise only see what was written at handle time.
 pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
+    env: Env,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
     match msg {
         QueryMsg::Stats {} => query_stats(deps),
-        _ => authenticated_queries(deps, msg), // deal with all authenticated queries
+        QueryMsg::WithPermit { permit, query } => permit_queries(deps, env, permit, query),
+        _ => authenticated_queries(deps, env, msg), // deal with all authenticated queries
+    }
+}
+
+// Authenticate via a signed SNIP-24 permit instead of a viewing key, then dispatch the
+// requested query for the permit's signer.
+fn permit_queries<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    permit: Permit,
+    query: QueryWithPermit,
+) -> QueryResult {
+    let config = Bincode2ReminderStoreRef::new(&deps.storage).config()?;
+    let signer_address = permit::validate(deps, &permit, config.contract_address)?;
+
+    match query {
+        QueryWithPermit::Read {} => query_read(deps, &env, &signer_address),
     }
 }
 
 fn query_stats<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
-    // retrieve the config state from storage
-    let config: State = load(&deps.storage, CONFIG_KEY)?;
+    let config = Bincode2ReminderStoreRef::new(&deps.storage).config()?;
     to_binary(&QueryAnswer::Stats {
         reminder_count: config.reminder_count,
     })
@@ -209,8 +359,15 @@ fn query_stats<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdRes
 // --> if the viewing key does not match or was not set, then we return an unauthorized error
 fn authenticated_queries<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
+    env: Env,
     msg: QueryMsg,
 ) -> QueryResult {
+    // `BatchRead` carries its own per-entry credentials (viewing key or permit), so each one
+    // is validated independently rather than through the single-address flow below.
+    if let QueryMsg::BatchRead { ref queries } = msg {
+        return query_batch_read(deps, &env, queries.clone());
+    }
+
     let (addresses, key) = msg.get_validation_params();
 
     for address in addresses {
@@ -224,7 +381,13 @@ fn authenticated_queries<S: Storage, A: Api, Q: Querier>(
             key.check_viewing_key(&[0u8; VIEWING_KEY_SIZE]);
         } else if key.check_viewing_key(expected_key.unwrap().as_slice()) {
             return match msg {
-                QueryMsg::Read { address, .. } => query_read(&deps, &address),
+                QueryMsg::Read { address, .. } => query_read(&deps, &env, &address),
+                QueryMsg::List {
+                    address,
+                    page,
+                    page_size,
+                    ..
+                } => query_list(&deps, &env, &address, page, page_size),
                 _ => panic!("This query type does not require authentication"),
             };
         }
@@ -233,33 +396,33 @@ fn authenticated_queries<S: Storage, A: Api, Q: Querier>(
     Err(StdError::unauthorized())
 }
 
-// Similarly to the try_read function, the query_read function uses the sender address to read and return the reminder - withouht paying any SCRT tokens!
-fn query_read<S: Storage, A: Api, Q: Querier>(
+// Shared by `query_read` and `query_batch_read`: look up the sender's most recent reminder via
+// `lookup_most_recent` - withouht paying any SCRT tokens! Unlike `try_read`, an expired reminder
+// can't be purged from storage here since queries never mutate state.
+fn read_most_recent<S: Storage, A: Api, Q: Querier, St: ReminderStore>(
     deps: &Extern<S, A, Q>,
+    store: &St,
+    env: &Env,
     address: &HumanAddr,
-) -> StdResult<Binary> {
-    let status: String;
-    let mut reminder: Option<String> = None;
-    let mut timestamp: Option<u64> = None;
-
+) -> StdResult<ReadResult> {
     let sender_address = deps.api.canonical_address(&address)?;
+    let lookup = lookup_most_recent(store, env, &sender_address)?;
+    Ok(reminder_lookup_to_result(lookup))
+}
 
-    // read the reminder from storage
-    let result: Option<Reminder> = may_load(&deps.storage, &sender_address.as_slice().to_vec())
-        .ok()
-        .unwrap();
-    match result {
-        // set all response field values
-        Some(stored_reminder) => {
-            status = String::from("Reminder found.");
-            reminder = String::from_utf8(stored_reminder.content).ok();
-            timestamp = Some(stored_reminder.timestamp);
-        }
-        // unless there's an error
-        None => {
-            status = String::from("Reminder not found.");
-        }
-    };
+// Similarly to the try_read function, the query_read function uses the sender address to read and
+// return the most recent reminder - withouht paying any SCRT tokens!
+fn query_read<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    address: &HumanAddr,
+) -> StdResult<Binary> {
+    let store = Bincode2ReminderStoreRef::new(&deps.storage);
+    let ReadResult {
+        status,
+        reminder,
+        timestamp,
+    } = read_most_recent(deps, &store, env, address)?;
 
     to_binary(&QueryAnswer::Read {
         status,
@@ -267,3 +430,92 @@ fn query_read<S: Storage, A: Api, Q: Querier>(
         timestamp,
     })
 }
+
+// Reads the most recent reminder for many addresses in one query. Each sub-query carries its
+// own viewing key or permit and is authenticated independently; unauthorized entries are
+// skipped rather than failing the whole batch.
+fn query_batch_read<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    queries: Vec<ReadQuery>,
+) -> QueryResult {
+    let store = Bincode2ReminderStoreRef::new(&deps.storage);
+    let mut results = Vec::with_capacity(queries.len());
+
+    for read_query in queries {
+        let authenticated_address = match read_query {
+            ReadQuery::ViewingKey { address, key } => {
+                // A malformed address is just another kind of failed authentication here - it
+                // must not abort the whole batch, same as a wrong viewing key.
+                match deps.api.canonical_address(&address) {
+                    Ok(canonical_addr) => {
+                        let expected_key = read_viewing_key(&deps.storage, &canonical_addr);
+                        let key = ViewingKey(key);
+
+                        match expected_key {
+                            Some(expected) if key.check_viewing_key(expected.as_slice()) => {
+                                Some(address)
+                            }
+                            _ => {
+                                // Checking the key still takes significant time even when it
+                                // can't match, for the same timing reasons as the single-query
+                                // path above.
+                                key.check_viewing_key(&[0u8; VIEWING_KEY_SIZE]);
+                                None
+                            }
+                        }
+                    }
+                    Err(_) => None,
+                }
+            }
+            ReadQuery::Permit { permit } => {
+                let config = store.config()?;
+                permit::validate(deps, &permit, config.contract_address).ok()
+            }
+        };
+
+        if let Some(address) = authenticated_address {
+            results.push(read_most_recent(deps, &store, env, &address)?);
+        }
+    }
+
+    to_binary(&QueryAnswer::BatchRead { results })
+}
+
+// Lists an address's reminders newest-first, `page_size` at a time starting at `page` (0-indexed).
+// Expired reminders are left out of the result but still take up their slot in the page (and
+// are still counted in `total`), so a page can hold fewer than `page_size` reminders.
+fn query_list<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    address: &HumanAddr,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let sender_address = deps.api.canonical_address(&address)?;
+    let store = Bincode2ReminderStoreRef::new(&deps.storage);
+    let total = store.reminder_count(&sender_address);
+
+    let skip = page.saturating_mul(page_size);
+    let mut reminders = Vec::new();
+    for i in 0..page_size {
+        let offset = skip + i;
+        if offset >= total {
+            break;
+        }
+        // newest-first: the most recent reminder has index `total - 1`
+        let stored_index = total - 1 - offset;
+        if let Some(stored_reminder) = store.get_reminder(&sender_address, stored_index)? {
+            if stored_reminder.expiration.is_expired(&env.block) {
+                continue;
+            }
+            reminders.push(ReminderWithId {
+                id: stored_index,
+                reminder: String::from_utf8(stored_reminder.content).ok(),
+                timestamp: stored_reminder.timestamp,
+            });
+        }
+    }
+
+    to_binary(&QueryAnswer::List { reminders, total })
+}
@@ -0,0 +1,29 @@
+use cosmwasm_std::BlockInfo;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// When a reminder stops being readable. Checked against the current block, not wall-clock
+// time, so behaviour stays deterministic across validators.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiration {
+    AtHeight(u64),
+    AtTime(u64),
+    Never,
+}
+
+impl Default for Expiration {
+    fn default() -> Self {
+        Expiration::Never
+    }
+}
+
+impl Expiration {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self {
+            Expiration::AtHeight(height) => block.height >= *height,
+            Expiration::AtTime(time) => block.time >= *time,
+            Expiration::Never => false,
+        }
+    }
+}
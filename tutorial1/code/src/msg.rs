@@ -1,6 +1,11 @@
+use cosmwasm_std::HumanAddr;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::expiration::Expiration;
+use crate::permit::Permit;
+use crate::viewing_key::ViewingKey;
+
 // -------------------------------------------------------------------------- //
 //                     init, handle, query MESSAGES                           //
 // -------------------------------------------------------------------------- //
@@ -11,12 +16,19 @@ pub struct InitMsg {
     pub prng_seed: String, // set a PRNG 'seed' String when the contract is first initialized
 }
 
+// Triggers the `migrate` entry point. Carries no fields today - future upgrades that need
+// caller-supplied parameters can add them here without breaking older migrations.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
-    // Records a new reminder for the sender
+    // Records a new reminder for the sender, appending it to their list. Defaults to
+    // `Expiration::Never` when no expiration is given, preserving the old non-expiring behavior.
     Record {
         reminder: String,
+        expiration: Option<Expiration>,
     },
     // Requests the current reminder for the sender
     Read {},
@@ -26,6 +38,24 @@ pub enum HandleMsg {
         entropy: String,
         padding: Option<String>, // padding is an optional parameter to obfuscate the length of the entropy string
     },
+
+    // Revoke a previously issued query permit by name, so it can no longer authenticate queries
+    RevokePermit {
+        permit_name: String,
+    },
+
+    // Records many reminders for the sender in a single transaction, amortizing gas and
+    // round-trips for clients syncing a batch of notes at once.
+    BatchRecord {
+        reminders: Vec<BatchRecordItem>,
+    },
+}
+
+// A single reminder within a `BatchRecord` message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchRecordItem {
+    pub content: String,
+    pub expiration: Option<Expiration>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -35,14 +65,52 @@ pub enum QueryMsg {
     Stats {},
 
     // when we make a 'Read' query we pass in the address of the querier using their
-    // human-friendly secret address and the viewing key string.
+    // human-friendly secret address and the viewing key string. Returns the most recent
+    // reminder for backwards compatibility with the single-reminder contract.
     Read { address: HumanAddr, key: String },
+
+    // Lists an address's reminders newest-first, `page_size` at a time starting at `page`
+    // (0-indexed).
+    List {
+        address: HumanAddr,
+        key: String,
+        page: u32,
+        page_size: u32,
+    },
+
+    // Authenticate with a signed SNIP-24 permit instead of a viewing key - no prior on-chain
+    // state (and no tx fee) is required to read gaslessly.
+    WithPermit {
+        permit: Permit,
+        query: QueryWithPermit,
+    },
+
+    // Reads the most recent reminder for many addresses in a single query, each authenticated
+    // independently. Unauthorized entries are skipped rather than failing the whole batch.
+    BatchRead {
+        queries: Vec<ReadQuery>,
+    },
+}
+
+// A single sub-query within a `BatchRead`, carrying its own credentials
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadQuery {
+    ViewingKey { address: HumanAddr, key: String },
+    Permit { permit: Permit },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    Read {},
 }
 
 impl QueryMsg {
     pub fn get_validation_params(&self) -> (Vec<&HumanAddr>, ViewingKey) {
         match self {
             Self::Read { address, key, .. } => (vec![address], ViewingKey(key.clone())),
+            Self::List { address, key, .. } => (vec![address], ViewingKey(key.clone())),
             _ => panic!("This query type does not require authentication"),
         }
     }
@@ -57,9 +125,11 @@ impl QueryMsg {
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleAnswer {
-    // Return a status message to let the user know if it succeeded or failed
+    // Return a status message (and the new reminder's index, if it was recorded) to let the
+    // user know if it succeeded or failed
     Record {
         status: String,
+        index: Option<u32>,
     },
     // Return a status message and the current reminder and its timestamp, if it exists
     Read {
@@ -71,6 +141,14 @@ pub enum HandleAnswer {
     GenerateViewingKey {
         key: ViewingKey,
     },
+
+    RevokePermit {
+        status: String,
+    },
+
+    BatchRecord {
+        statuses: Vec<String>,
+    },
 }
 
 // Responses from query functions
@@ -87,4 +165,29 @@ pub enum QueryAnswer {
         reminder: Option<String>,
         timestamp: Option<u64>,
     },
+
+    List {
+        reminders: Vec<ReminderWithId>,
+        total: u32,
+    },
+
+    BatchRead {
+        results: Vec<ReadResult>,
+    },
+}
+
+// A single reminder together with its index in the address's reminder list
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct ReminderWithId {
+    pub id: u32,
+    pub reminder: Option<String>,
+    pub timestamp: u64,
+}
+
+// The result of one sub-query within a `BatchRead`
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct ReadResult {
+    pub status: String,
+    pub reminder: Option<String>,
+    pub timestamp: Option<u64>,
 }
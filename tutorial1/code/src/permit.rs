@@ -0,0 +1,172 @@
+use bech32::ToBase32;
+use cosmwasm_std::{Api, Binary, CanonicalAddr, Extern, HumanAddr, Querier, StdError, StdResult, Storage};
+use ripemd160::{Digest, Ripemd160};
+use schemars::JsonSchema;
+use secp256k1::{Message, PublicKey, Secp256k1, Signature};
+use secret_toolkit::crypto::sha_256;
+use serde::{Deserialize, Serialize};
+
+use crate::state::read_revoked_permit;
+
+// -------------------------------------------------------------------------- //
+//                           SNIP-24 query permits                            //
+// -------------------------------------------------------------------------- //
+// A permit is a message signed off-chain by the user's wallet (no tx, no gas)
+// that authorizes a set of queries against a set of contracts for as long as
+// the permit's name has not been revoked.
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Permit {
+    pub params: PermitParams,
+    pub signature: PermitSignature,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitParams {
+    pub allowed_tokens: Vec<HumanAddr>,
+    pub permit_name: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Owner,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitSignature {
+    pub pub_key: PubKey,
+    pub signature: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PubKey {
+    // ignoring the "type" field - assumed to be tendermint/PubKeySecp256k1
+    pub value: Binary,
+}
+
+// Minimal representation of the StdSignDoc the wallet actually signs, so we
+// can reconstruct the exact bytes that went into the signature. The sign doc's JSON keys are
+// sorted, so the fields are declared in alphabetical order.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct SignedMessage {
+    account_number: String,
+    chain_id: String,
+    fee: Fee,
+    memo: String,
+    msgs: Vec<PermitMsg>,
+    sequence: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct Fee {
+    amount: Vec<Coin>,
+    gas: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct Coin {
+    amount: String,
+    denom: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct PermitMsg {
+    #[serde(rename = "type")]
+    msg_type: String,
+    value: PermitParams,
+}
+
+fn signed_message(params: &PermitParams) -> SignedMessage {
+    SignedMessage {
+        chain_id: "secret-4".to_string(),
+        account_number: "0".to_string(),
+        sequence: "0".to_string(),
+        fee: Fee {
+            amount: vec![Coin {
+                amount: "0".to_string(),
+                denom: "uscrt".to_string(),
+            }],
+            gas: "1".to_string(),
+        },
+        msgs: vec![PermitMsg {
+            msg_type: "query_permit".to_string(),
+            value: params.clone(),
+        }],
+        memo: "".to_string(),
+    }
+}
+
+// Derive a bech32 HumanAddr from a compressed secp256k1 public key, the same
+// way the chain derives account addresses: bech32(ripemd160(sha256(pubkey))).
+fn pubkey_to_address(pubkey: &[u8]) -> StdResult<HumanAddr> {
+    let sha_hash = sha_256(pubkey);
+    let mut hasher = Ripemd160::new();
+    hasher.update(sha_hash);
+    let raw_addr = hasher.finalize();
+
+    bech32::encode("secret", raw_addr.to_base32())
+        .map(HumanAddr)
+        .map_err(|err| StdError::generic_err(format!("failed to bech32-encode address: {}", err)))
+}
+
+/// Validate a permit and return the `HumanAddr` of the signer.
+///
+/// This reconstructs the `StdSignDoc` JSON the wallet actually signed,
+/// hashes it, verifies the secp256k1 signature against the supplied public
+/// key, derives the signer's address from that public key, and confirms
+/// that `current_contract_addr` is in `allowed_tokens`, that `permissions`
+/// grants `Owner`, and that the permit has not been revoked by its signer.
+pub fn validate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: &Permit,
+    current_contract_addr: HumanAddr,
+) -> StdResult<HumanAddr> {
+    if !permit
+        .params
+        .allowed_tokens
+        .iter()
+        .any(|addr| addr == &current_contract_addr)
+    {
+        return Err(StdError::generic_err(format!(
+            "Permit doesn't apply to token {:?}, allowed tokens: {:?}",
+            current_contract_addr, permit.params.allowed_tokens
+        )));
+    }
+
+    if !permit.params.permissions.contains(&Permission::Owner) {
+        return Err(StdError::generic_err(format!(
+            "Permit does not grant the \"owner\" permission, permissions: {:?}",
+            permit.params.permissions
+        )));
+    }
+
+    let signer_address = pubkey_to_address(permit.signature.pub_key.value.as_slice())?;
+
+    let signer_canonical: CanonicalAddr = deps.api.canonical_address(&signer_address)?;
+    if read_revoked_permit(&deps.storage, &signer_canonical, &permit.params.permit_name) {
+        return Err(StdError::generic_err(format!(
+            "Permit \"{}\" was revoked by account {:?}",
+            permit.params.permit_name, signer_address
+        )));
+    }
+
+    let sign_bytes = serde_json_wasm::to_vec(&signed_message(&permit.params))
+        .map_err(|err| StdError::generic_err(format!("failed to serialize sign doc: {}", err)))?;
+    let sign_hash = sha_256(&sign_bytes);
+
+    let secp256k1_msg = Message::from_slice(&sign_hash)
+        .map_err(|err| StdError::generic_err(format!("invalid signature hash: {}", err)))?;
+    let secp256k1_sig = Signature::from_compact(permit.signature.signature.as_slice())
+        .map_err(|err| StdError::generic_err(format!("invalid signature: {}", err)))?;
+    let secp256k1_pubkey = PublicKey::from_slice(permit.signature.pub_key.value.as_slice())
+        .map_err(|err| StdError::generic_err(format!("invalid public key: {}", err)))?;
+
+    let secp256k1_verifier = Secp256k1::verification_only();
+    secp256k1_verifier
+        .verify(&secp256k1_msg, &secp256k1_sig, &secp256k1_pubkey)
+        .map_err(|_| StdError::unauthorized())?;
+
+    Ok(signer_address)
+}
@@ -1,27 +1,106 @@
-use cosmwasm_std::{ReadonlyStorage, StdError, StdResult, Storage};
+use cosmwasm_std::{CanonicalAddr, HumanAddr, ReadonlyStorage, StdError, StdResult, Storage};
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
 use secret_toolkit::serialization::{Bincode2, Serde};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::any::type_name;
 
+use crate::expiration::Expiration;
+use crate::viewing_key::ViewingKey;
+
 // -------------------------------------------------------------------------- //
 //                              contract state                                //
 // -------------------------------------------------------------------------- //
 pub static CONFIG_KEY: &[u8] = b"config";
 
+// Bincode2 isn't self-describing, so a `State` blob can't be told apart from some other
+// schema's blob by just trying to deserialize it and seeing whether that happens to succeed -
+// field sizes can coincidentally line up. Every `State` saved from version 1 onward also gets
+// its version written here, as a plain big-endian `u16`, so `migrate_state` has an unambiguous
+// tag to branch on instead. Its absence means the data predates versioning entirely, i.e. it's
+// the original `StateV0` layout.
+pub static STATE_VERSION_KEY: &[u8] = b"stateversion";
+
+// Bump whenever `State`'s layout or storage keying changes, and teach `migrate_state` how to
+// upgrade from the previous version.
+pub const CONTRACT_VERSION: u16 = 1;
+
 // State information for the contract
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct State {
+    pub version: u16,
     pub max_size: u16,
     pub reminder_count: u64,
     pub prng_seed: Vec<u8>,
+    pub contract_address: HumanAddr,
 }
 
-// Reminder message and timestamp
+// The pre-migration (version 0) schema: no `version` field, no `contract_address`, and each
+// address's reminder was saved directly under its canonical address instead of the
+// append-only list kept under `PREFIX_REMINDERS`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct StateV0 {
+    pub max_size: u16,
+    pub reminder_count: u64,
+    pub prng_seed: Vec<u8>,
+}
+
+// Load the on-chain `State`, upgrading it in place if it was written by an older contract
+// version. Rejects downgrades and unrecognized versions with a clear error.
+pub fn migrate_state<S: Storage>(storage: &S, contract_address: HumanAddr) -> StdResult<State> {
+    let raw = storage
+        .get(CONFIG_KEY)
+        .ok_or_else(|| StdError::not_found(type_name::<State>()))?;
+
+    // Branch on the explicit `STATE_VERSION_KEY` tag, not on whether deserializing `raw` as a
+    // `State` happens to succeed.
+    match storage.get(STATE_VERSION_KEY) {
+        Some(tag) => {
+            let mut buf = [0u8; 2];
+            buf.copy_from_slice(&tag);
+            let version = u16::from_be_bytes(buf);
+
+            match version {
+                v if v == CONTRACT_VERSION => Bincode2::deserialize::<State>(&raw)
+                    .map_err(|err| StdError::generic_err(format!("Corrupt state: {}", err))),
+                v if v > CONTRACT_VERSION => Err(StdError::generic_err(format!(
+                    "Cannot downgrade contract from version {} to {}",
+                    v, CONTRACT_VERSION
+                ))),
+                v => Err(StdError::generic_err(format!(
+                    "Don't know how to migrate from unrecognized version {}",
+                    v
+                ))),
+            }
+        }
+        // No version tag was ever written, so this predates versioning entirely - the original,
+        // unversioned schema.
+        None => {
+            let old: StateV0 = Bincode2::deserialize(&raw)
+                .map_err(|_| StdError::generic_err("Unrecognized contract state, cannot migrate"))?;
+
+            // NOTE: version 0 kept no index of which addresses had recorded a reminder, so its
+            // one-reminder-per-address entries (keyed directly by canonical address) can't be
+            // enumerated here and are left in place at their old keys. They're simply
+            // superseded: reads go through the version 1 append-store keying from now on, so
+            // migrated accounts look empty until they `Record` again.
+            Ok(State {
+                version: CONTRACT_VERSION,
+                max_size: old.max_size,
+                reminder_count: old.reminder_count,
+                prng_seed: old.prng_seed,
+                contract_address,
+            })
+        }
+    }
+}
+
+// Reminder message, timestamp, and optional expiration
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Reminder {
     pub content: Vec<u8>,
     pub timestamp: u64,
+    pub expiration: Expiration,
 }
 
 // -------------------------------------------------------------------------- //
@@ -69,3 +148,201 @@ pub fn read_viewing_key<S: Storage>(store: &S, owner: &CanonicalAddr) -> Option<
     let user_key_store = ReadonlyPrefixedStorage::new(PREFIX_VIEWING_KEY, store);
     user_key_store.get(owner.as_slice())
 }
+
+// to read and write revoked query permits, keyed by signer address and permit name
+pub const PREFIX_REVOKED_PERMITS: &[u8] = b"revokedpermits";
+
+fn revoked_permit_key(owner: &CanonicalAddr, permit_name: &str) -> Vec<u8> {
+    [owner.as_slice(), permit_name.as_bytes()].concat()
+}
+
+// mark a permit name as revoked for a given signer
+pub fn revoke_permit<S: Storage>(store: &mut S, owner: &CanonicalAddr, permit_name: &str) {
+    let mut permits_store = PrefixedStorage::new(PREFIX_REVOKED_PERMITS, store);
+    permits_store.set(&revoked_permit_key(owner, permit_name), &[1u8]);
+}
+
+// check whether a permit name has been revoked by its signer
+pub fn read_revoked_permit<S: ReadonlyStorage>(
+    store: &S,
+    owner: &CanonicalAddr,
+    permit_name: &str,
+) -> bool {
+    let permits_store = ReadonlyPrefixedStorage::new(PREFIX_REVOKED_PERMITS, store);
+    permits_store
+        .get(&revoked_permit_key(owner, permit_name))
+        .is_some()
+}
+
+// -------------------------------------------------------------------------- //
+//                     per-address append-only reminder list                  //
+// -------------------------------------------------------------------------- //
+// Each address owns an ordered, append-only list of reminders: a length kept
+// under PREFIX_REMINDERS_LEN and the entries themselves kept under
+// PREFIX_REMINDERS, keyed by the address plus a big-endian index so a second
+// `Record` no longer overwrites the first.
+pub const PREFIX_REMINDERS: &[u8] = b"reminders";
+pub const PREFIX_REMINDERS_LEN: &[u8] = b"reminderslen";
+
+fn reminder_entry_key(owner: &CanonicalAddr, index: u32) -> Vec<u8> {
+    [owner.as_slice(), &index.to_be_bytes()].concat()
+}
+
+// the number of reminders recorded for an address so far
+pub fn reminder_count<S: ReadonlyStorage>(store: &S, owner: &CanonicalAddr) -> u32 {
+    let len_store = ReadonlyPrefixedStorage::new(PREFIX_REMINDERS_LEN, store);
+    match len_store.get(owner.as_slice()) {
+        Some(bytes) => {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(&bytes);
+            u32::from_be_bytes(buf)
+        }
+        None => 0,
+    }
+}
+
+// push a new reminder onto an address's list and return its index
+pub fn append_reminder<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    reminder: &Reminder,
+) -> StdResult<u32> {
+    let index = reminder_count(store, owner);
+
+    let mut reminders_store = PrefixedStorage::new(PREFIX_REMINDERS, store);
+    save(&mut reminders_store, &reminder_entry_key(owner, index), reminder)?;
+    drop(reminders_store);
+
+    let mut len_store = PrefixedStorage::new(PREFIX_REMINDERS_LEN, store);
+    len_store.set(owner.as_slice(), &(index + 1).to_be_bytes());
+
+    Ok(index)
+}
+
+// read a single reminder by its index in an address's list
+pub fn get_reminder<S: ReadonlyStorage>(
+    store: &S,
+    owner: &CanonicalAddr,
+    index: u32,
+) -> StdResult<Option<Reminder>> {
+    let reminders_store = ReadonlyPrefixedStorage::new(PREFIX_REMINDERS, store);
+    may_load(&reminders_store, &reminder_entry_key(owner, index))
+}
+
+// drop an address's most recently recorded reminder, e.g. because it has expired
+pub fn remove_latest_reminder<S: Storage>(store: &mut S, owner: &CanonicalAddr) {
+    let count = reminder_count(store, owner);
+    if count == 0 {
+        return;
+    }
+    let index = count - 1;
+
+    let mut reminders_store = PrefixedStorage::new(PREFIX_REMINDERS, store);
+    reminders_store.remove(&reminder_entry_key(owner, index));
+    drop(reminders_store);
+
+    let mut len_store = PrefixedStorage::new(PREFIX_REMINDERS_LEN, store);
+    len_store.set(owner.as_slice(), &index.to_be_bytes());
+}
+
+// -------------------------------------------------------------------------- //
+//                 ReminderStore: storage behind a trait                      //
+// -------------------------------------------------------------------------- //
+// `contract.rs` used to call the free functions above directly, which hardwires the Bincode2 +
+// prefixed-storage layout into every handler. Going through this trait instead isolates that
+// keying scheme in one place, so it can evolve without touching handler logic again. (This repo
+// has no test harness yet, so the trait isn't backed by a mock implementation today - but any
+// future one only needs to implement `ReminderStore`/`ReminderStoreMut`, not touch `contract.rs`.)
+
+// Operations that only need a shared reference to storage. `query` entry points are only ever
+// handed a `&Extern`, never a `&mut Extern`, so this is the trait they use.
+pub trait ReminderStore {
+    fn config(&self) -> StdResult<State>;
+    fn get_reminder(&self, owner: &CanonicalAddr, index: u32) -> StdResult<Option<Reminder>>;
+    fn reminder_count(&self, owner: &CanonicalAddr) -> u32;
+}
+
+// Mutating operations, only available where storage is held mutably, i.e. from `handle`.
+pub trait ReminderStoreMut: ReminderStore {
+    fn set_config(&mut self, config: &State) -> StdResult<()>;
+    fn put_reminder(&mut self, owner: &CanonicalAddr, reminder: &Reminder) -> StdResult<u32>;
+    fn remove_latest_reminder(&mut self, owner: &CanonicalAddr);
+
+    // load, increment, and save `config.reminder_count` in one step
+    fn bump_count(&mut self) -> StdResult<()> {
+        let mut config = self.config()?;
+        config.reminder_count += 1;
+        self.set_config(&config)
+    }
+}
+
+// Read-only `ReminderStore`, backed by a shared storage reference. What `query` entry points
+// construct, since they never see a `&mut Extern`.
+pub struct Bincode2ReminderStoreRef<'a, S: ReadonlyStorage> {
+    storage: &'a S,
+}
+
+impl<'a, S: ReadonlyStorage> Bincode2ReminderStoreRef<'a, S> {
+    pub fn new(storage: &'a S) -> Self {
+        Self { storage }
+    }
+}
+
+impl<'a, S: ReadonlyStorage> ReminderStore for Bincode2ReminderStoreRef<'a, S> {
+    fn config(&self) -> StdResult<State> {
+        load(self.storage, CONFIG_KEY)
+    }
+
+    fn get_reminder(&self, owner: &CanonicalAddr, index: u32) -> StdResult<Option<Reminder>> {
+        get_reminder(self.storage, owner, index)
+    }
+
+    fn reminder_count(&self, owner: &CanonicalAddr) -> u32 {
+        reminder_count(self.storage, owner)
+    }
+}
+
+// Read-write `ReminderStore`/`ReminderStoreMut`, backed by the same Bincode2 + prefixed-storage
+// layout as `Bincode2ReminderStoreRef`. What `handle` entry points construct, since they're
+// always handed a `&mut Extern`.
+pub struct Bincode2ReminderStore<'a, S: Storage> {
+    storage: &'a mut S,
+}
+
+impl<'a, S: Storage> Bincode2ReminderStore<'a, S> {
+    pub fn new(storage: &'a mut S) -> Self {
+        Self { storage }
+    }
+}
+
+impl<'a, S: Storage> ReminderStore for Bincode2ReminderStore<'a, S> {
+    fn config(&self) -> StdResult<State> {
+        load(self.storage, CONFIG_KEY)
+    }
+
+    fn get_reminder(&self, owner: &CanonicalAddr, index: u32) -> StdResult<Option<Reminder>> {
+        get_reminder(self.storage, owner, index)
+    }
+
+    fn reminder_count(&self, owner: &CanonicalAddr) -> u32 {
+        reminder_count(self.storage, owner)
+    }
+}
+
+impl<'a, S: Storage> ReminderStoreMut for Bincode2ReminderStore<'a, S> {
+    fn set_config(&mut self, config: &State) -> StdResult<()> {
+        // Keep `STATE_VERSION_KEY` in lockstep with every `State` write, so `migrate_state`
+        // always has an explicit tag to branch on.
+        self.storage
+            .set(STATE_VERSION_KEY, &config.version.to_be_bytes());
+        save(self.storage, CONFIG_KEY, config)
+    }
+
+    fn put_reminder(&mut self, owner: &CanonicalAddr, reminder: &Reminder) -> StdResult<u32> {
+        append_reminder(self.storage, owner, reminder)
+    }
+
+    fn remove_latest_reminder(&mut self, owner: &CanonicalAddr) {
+        remove_latest_reminder(self.storage, owner)
+    }
+}